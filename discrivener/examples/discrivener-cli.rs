@@ -2,6 +2,7 @@ use clap::Parser;
 use colored::Colorize;
 use discrivener::api;
 use discrivener::api_types;
+use discrivener::{VoiceActivityConfig, WhisperConfig, WhisperSamplingStrategy};
 use std::sync::Arc;
 use tokio::signal;
 
@@ -38,11 +39,38 @@ fn on_text(message: api_types::TranscribedMessage, log_performance: bool) {
 #[tokio::main]
 async fn tokio_main(cli: Cli) {
     let log_performance = cli.log_performance;
+    let whisper_config = WhisperConfig {
+        language: cli.language,
+        translate: cli.translate,
+        sampling_strategy: match cli.beam_size {
+            Some(beam_size) => WhisperSamplingStrategy::BeamSearch {
+                beam_size,
+                patience: -1.0,
+            },
+            None => WhisperSamplingStrategy::Greedy { best_of: 1 },
+        },
+        initial_prompt: cli.initial_prompt,
+        n_threads: cli.n_threads.unwrap_or_else(|| WhisperConfig::default().n_threads),
+        temperature_fallback: WhisperConfig::default().temperature_fallback,
+        max_concurrent_transcriptions: WhisperConfig::default().max_concurrent_transcriptions,
+        min_energy_to_transcribe: WhisperConfig::default().min_energy_to_transcribe,
+    };
+    let voice_activity_config = VoiceActivityConfig {
+        silence_threshold: cli.silence_threshold,
+        silence_gap_ms: cli.silence_gap_ms,
+        stop_talking_flush_delay_ms: cli.stop_talking_flush_delay_ms,
+    };
     let mut discrivener = api::Discrivener::load(
         cli.model_path,
         Arc::new(move |message| on_text(message, log_performance)),
         cli.save_everything_to_file,
-    );
+        whisper_config,
+        voice_activity_config,
+        cli.mix_output,
+        std::collections::HashMap::new(),
+        cli.capture_soundshare,
+    )
+    .expect("failed to set up mixdown output file");
 
     let connection_result = discrivener
         .connect(
@@ -94,6 +122,51 @@ struct Cli {
 
     #[arg(long, default_value = None)]
     save_everything_to_file: Option<String>,
+
+    /// Spoken language hint (ISO 639-1, e.g. "en"). Auto-detected if omitted.
+    #[arg(long, default_value = None)]
+    language: Option<String>,
+
+    /// Translate recognized speech into English.
+    #[arg(long, default_value = "false")]
+    translate: bool,
+
+    /// Use beam search with this beam width instead of greedy decoding.
+    #[arg(long, default_value = None)]
+    beam_size: Option<i32>,
+
+    /// Text used to bias decoding, e.g. expected names or jargon.
+    #[arg(long, default_value = None)]
+    initial_prompt: Option<String>,
+
+    /// CPU threads whisper.cpp may use per decode. Defaults to the number
+    /// of logical cores.
+    #[arg(long, default_value = None)]
+    n_threads: Option<usize>,
+
+    /// RMS energy below which a 20ms chunk is considered silent.
+    #[arg(long, default_value = "200")]
+    silence_threshold: i32,
+
+    /// How long a run of silence must last before a clip is flushed early.
+    #[arg(long, default_value = "1000")]
+    silence_gap_ms: u32,
+
+    /// How long to wait after Discord reports a user has stopped talking
+    /// before flushing their clip, in case they resume within the same
+    /// breath.
+    #[arg(long, default_value = "500")]
+    stop_talking_flush_delay_ms: u32,
+
+    /// If set, write a combined recording of every speaker to this path, as
+    /// a 48kHz stereo WAV file.
+    #[arg(long, default_value = None)]
+    mix_output: Option<String>,
+
+    /// Also transcribe screen-share ("soundshare") audio, not just
+    /// microphone audio.
+    #[arg(long, default_value = "false")]
+    capture_soundshare: bool,
 }
 
 fn main() {
@@ -1,5 +1,6 @@
 use clap::Parser;
 use discrivener::api;
+use discrivener::{VoiceActivityConfig, WhisperConfig};
 use serde_json;
 use std::sync::Arc;
 use tokio::signal;
@@ -10,7 +11,13 @@ async fn tokio_main(cli: Cli) {
         cli.model_path,
         Arc::new(|event| println!("{}", serde_json::to_string(&event).unwrap())),
         cli.save_everything_to_file,
-    );
+        WhisperConfig::default(),
+        VoiceActivityConfig::default(),
+        None,
+        std::collections::HashMap::new(),
+        false,
+    )
+    .expect("failed to set up mixdown output file");
 
     let connection_result = discrivener
         .connect(
@@ -7,17 +7,69 @@ use songbird::ConnectionInfo;
 #[pyclass]
 pub struct Discrivener {
     model: crate::model::Model,
+    current_playback: Option<songbird::tracks::TrackHandle>,
 }
 
+// `load` takes a raw Rust callback (`Arc<dyn Fn(TranscribedMessage) + ...>`)
+// and `connect`/`play_audio_stream` are async / take a `std::sync::mpsc`
+// channel -- none of which pyo3 can convert a plain Python object into
+// without an adapter (wrapping a Python callable as that Arc, bridging the
+// async runtime) that nothing else in this crate has needed yet. Left
+// un-exposed to Python here, same as before this backlog; only the
+// synchronous, argument-wise pyo3-compatible playback controls below are
+// bound, which is what's needed to trigger TTS playback from Python.
 impl Discrivener {
+    /// mix_output_path:
+    /// If set, write a combined recording of every speaker to this path, as
+    /// a 48kHz stereo WAV file.
+    ///
+    /// user_gains:
+    /// Playback volume for each user in the combined recording above,
+    /// relative to 1.0 (unity gain). Users not present default to 1.0.
+    /// Ignored if `mix_output_path` is `None`.
+    ///
+    /// capture_soundshare:
+    /// Also buffer and transcribe screen-share ("soundshare") audio, not
+    /// just microphone audio. Off by default, since soundshare is usually a
+    /// video call's desktop/game audio rather than someone talking.
     pub fn load(
         model_path: String,
         text_callback: std::sync::Arc<dyn Fn(api_types::TranscribedMessage) + Send + Sync>,
         dump_everything_to_a_file: Option<String>,
-    ) -> Self {
-        return Discrivener {
-            model: crate::model::Model::load(model_path, dump_everything_to_a_file, text_callback),
+        whisper_config: crate::whisper::WhisperConfig,
+        voice_activity_config: crate::voice_buffer::VoiceActivityConfig,
+        mix_output_path: Option<String>,
+        user_gains: std::collections::HashMap<u64, f32>,
+        capture_soundshare: bool,
+    ) -> std::io::Result<Self> {
+        let capture_config = crate::packet_handler::CaptureConfig {
+            capture_soundshare,
+            ..Default::default()
+        };
+
+        let mixer = match mix_output_path {
+            Some(path) => {
+                let mixer = crate::mixer::Mixer::new(crate::mixer::wav_file_callback(path)?);
+                for (user_id, gain) in user_gains {
+                    mixer.set_user_gain(user_id, gain);
+                }
+                Some(mixer)
+            }
+            None => None,
         };
+
+        return Ok(Discrivener {
+            model: crate::model::Model::load(
+                model_path,
+                dump_everything_to_a_file,
+                text_callback,
+                whisper_config,
+                voice_activity_config,
+                capture_config,
+                mixer,
+            ),
+            current_playback: None,
+        });
     }
 
     /// Connect to a voice channel.
@@ -57,4 +109,114 @@ impl Discrivener {
     pub fn disconnect(&mut self) {
         self.model.disconnect();
     }
+
+    /// Like `play_audio`, but for audio synthesized incrementally: send PCM
+    /// frames over `frames` as they become available, and they'll be
+    /// resampled, Opus-encoded, and played as they arrive. Playback stops
+    /// once the sending half of `frames` is dropped.
+    ///
+    /// Stops whatever was previously playing through this `Discrivener`,
+    /// so only one clip plays at a time; use `stop_audio` to interrupt it
+    /// early.
+    ///
+    /// Takes a `std::sync::mpsc::Receiver`, which pyo3 has no conversion
+    /// for, so (unlike `play_audio`) this isn't exposed to Python.
+    pub fn play_audio_stream(
+        &mut self,
+        frames: std::sync::mpsc::Receiver<Vec<i16>>,
+        sample_rate: u32,
+        channels: u16,
+    ) {
+        self.stop_audio();
+        self.current_playback =
+            Some(self.model.play_audio_stream(frames, sample_rate, channels));
+    }
+}
+
+#[pymethods]
+impl Discrivener {
+    /// Play a clip of PCM audio (e.g. synthesized TTS speech) back into the
+    /// connected voice channel.
+    ///
+    /// Stops whatever was previously playing through this `Discrivener`,
+    /// so only one clip plays at a time; use `stop_audio` to interrupt it
+    /// early.
+    ///
+    /// samples:
+    /// Raw PCM samples, interleaved if `channels` > 1.
+    ///
+    /// sample_rate:
+    /// Sample rate of `samples`, in Hz. Resampled to Discord's 48kHz stereo
+    /// internally, so the caller does not need to pre-convert.
+    pub fn play_audio(&mut self, samples: Vec<i16>, sample_rate: u32, channels: u16) {
+        self.stop_audio();
+        self.current_playback = Some(self.model.play_audio(samples, sample_rate, channels));
+    }
+
+    /// Stop any clip started by `play_audio`, if one is still playing.
+    pub fn stop_audio(&mut self) {
+        if let Some(handle) = self.current_playback.take() {
+            let _ = handle.stop();
+        }
+    }
+
+    /// Whether a clip started by `play_audio` is still playing.
+    pub fn is_playing(&self) -> bool {
+        self.current_playback.is_some()
+    }
+}
+
+/// Transcribes audio captured from a local input device instead of a
+/// Discord voice connection -- useful for desktop dictation, local meeting
+/// transcription, or exercising the pipeline without a live Discord session.
+///
+/// Rust-only for now: `load`'s `text_callback` is a raw `Arc<dyn Fn(...)>`,
+/// and its other parameters (`WhisperConfig`, `VoiceActivityConfig`) aren't
+/// `#[pyclass]` types either, so there's no callback-free method pyo3 could
+/// bind here -- unlike `Discrivener`, which still offers the synchronous
+/// playback controls even with `load`/`connect` unexposed. Not registered
+/// in the `discrivener` pymodule (see lib.rs), since a class with no usable
+/// constructor or methods is just dead surface for Python callers.
+pub struct MicDiscrivener {
+    _whisper: std::sync::Arc<crate::whisper::Whisper>,
+    _capture: crate::mic::MicCapture,
+}
+
+impl MicDiscrivener {
+    /// Start transcribing audio from a local input device.
+    ///
+    /// device_name:
+    /// Name of the capture device to open, as reported by the OS audio
+    /// subsystem. `None` uses the system default input device.
+    pub fn load(
+        model_path: String,
+        text_callback: std::sync::Arc<dyn Fn(api_types::TranscribedMessage) + Send + Sync>,
+        whisper_config: crate::whisper::WhisperConfig,
+        voice_activity_config: crate::voice_buffer::VoiceActivityConfig,
+        device_name: Option<String>,
+    ) -> Result<Self, String> {
+        let event_callback = std::sync::Arc::new(move |event| {
+            if let api_types::VoiceChannelEvent::TranscribedMessage(message) = event {
+                text_callback(message);
+            }
+        });
+        let whisper = std::sync::Arc::new(crate::whisper::Whisper::load(
+            model_path,
+            event_callback,
+            whisper_config,
+        ));
+
+        let whisper_for_capture = whisper.clone();
+        let audio_callback: crate::types::AudioCallback =
+            std::sync::Arc::new(move |user_id, audio, clip_start_unixsecs| {
+                whisper_for_capture.on_audio_complete(user_id, audio, clip_start_unixsecs)
+            });
+        let capture =
+            crate::mic::MicCapture::start(device_name, audio_callback, voice_activity_config)?;
+
+        return Ok(MicDiscrivener {
+            _whisper: whisper,
+            _capture: capture,
+        });
+    }
 }
@@ -9,9 +9,11 @@ pub struct UserJoinData {
 
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default, Serialize, Deserialize)]
 pub struct TranscribedMessage {
-    /// absolute time this message was received,
-    /// as reported by the Discord server
-    /// (NOT the local machine time)
+    /// unix-epoch second at which the first audio sample of this clip was
+    /// received locally. Discord's RTP stream carries no wall-clock time to
+    /// attach to a voice packet (its timestamps are an arbitrary per-SSRC
+    /// sample counter), so this is the local machine's clock, not a value
+    /// reported by the Discord server.
     pub timestamp: u64,
 
     /// Discord user id of the speaker
@@ -144,6 +146,16 @@ pub struct DisconnectData {
     pub session_id: String,
 }
 
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default, Serialize, Deserialize)]
+pub struct FallingBehindData {
+    /// Clips dropped over the rolling window used to compute `drop_rate_percent`.
+    pub dropped_clips: u32,
+    /// Total clips submitted for transcription over that same window.
+    pub total_clips: u32,
+    /// `dropped_clips / total_clips`, as a whole-number percentage.
+    pub drop_rate_percent: u32,
+}
+
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Serialize, Deserialize)]
 pub enum VoiceChannelEvent {
     UserJoin(UserJoinData),
@@ -151,4 +163,8 @@ pub enum VoiceChannelEvent {
     Connect(ConnectData),
     Reconnect(ConnectData),
     Disconnect(DisconnectData),
+    /// The transcription pipeline is dropping clips faster than a fixed
+    /// drop-rate threshold allows, usually because decoding can't keep up
+    /// with how much audio is coming in.
+    FallingBehind(FallingBehindData),
 }
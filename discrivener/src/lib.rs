@@ -2,16 +2,23 @@ use pyo3::prelude::*;
 
 pub mod api;
 pub mod api_types;
+mod mic;
+mod mixer;
 mod model;
 mod packet_handler;
 mod types;
 mod voice_buffer;
 mod whisper;
 
+pub use voice_buffer::VoiceActivityConfig;
+pub use whisper::{WhisperConfig, WhisperSamplingStrategy};
+
 /// A Python module implemented in Rust.
 #[pymodule]
 fn discrivener(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<api::Discrivener>()?;
+    // MicDiscrivener is Rust-only for now: see the note on its definition
+    // in api.rs for why it has no usable pyo3 constructor or methods yet.
     m.add_class::<api_types::TranscribedMessage>()?;
     m.add_class::<api_types::TextSegment>()?;
     Ok(())
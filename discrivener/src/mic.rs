@@ -0,0 +1,140 @@
+/// Captures audio from a local input device (rather than a Discord voice
+/// connection), so the transcription pipeline can be used for desktop
+/// dictation or local meeting transcription, and tested without a live
+/// Discord session.
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use crate::types;
+use crate::voice_buffer;
+
+/// Synthetic user id assigned to audio captured from a local microphone,
+/// since there's no Discord user to attribute it to.
+pub const LOCAL_MIC_USER_ID: types::UserId = 0;
+
+/// Captures audio from a local input device via cpal, converts it into the
+/// same 48kHz-stereo [`types::AudioClip`] shape songbird delivers, and feeds
+/// it through a [`voice_buffer::VoiceBufferForUser`] in
+/// [`types::AUDIO_SAMPLES_PER_FRAME`] chunks -- the same VAD/silence-flush
+/// path `packet_handler::PacketHandler` uses for Discord audio, so
+/// `audio_callback` only fires on a real flushed utterance rather than once
+/// per 20ms frame.
+pub struct MicCapture {
+    // kept alive for the duration of capture; dropping it stops the stream.
+    _stream: cpal::Stream,
+}
+
+impl MicCapture {
+    /// Open `device_name` (or the system default input device, if `None`)
+    /// and start streaming audio to `audio_callback`.
+    pub fn start(
+        device_name: Option<String>,
+        audio_callback: types::AudioCallback,
+        voice_activity_config: voice_buffer::VoiceActivityConfig,
+    ) -> Result<Self, String> {
+        let host = cpal::default_host();
+        let device = match device_name {
+            Some(name) => host
+                .input_devices()
+                .map_err(|err| err.to_string())?
+                .find(|d| d.name().map(|n| n == name).unwrap_or(false))
+                .ok_or_else(|| format!("no input device named '{}'", name))?,
+            None => host
+                .default_input_device()
+                .ok_or_else(|| "no default input device".to_string())?,
+        };
+
+        let supported_config = device.default_input_config().map_err(|err| err.to_string())?;
+        let channels = supported_config.channels() as usize;
+        let sample_rate = supported_config.sample_rate().0;
+
+        // audio not yet long enough to make a full AUDIO_SAMPLES_PER_FRAME
+        // chunk; carried over between callback invocations.
+        let pending = Arc::new(Mutex::new(Vec::<types::AudioSample>::new()));
+        let pending_for_stream = pending.clone();
+
+        // there's no Discord user here, so there's also no real SSRC/RTP
+        // stream -- just run a single buffer under the synthetic mic user
+        // id, fed by a locally-synthesized, monotonically increasing
+        // timestamp (in samples-per-channel) standing in for an RTP clock.
+        let voice_buffer = Arc::new(Mutex::new(voice_buffer::VoiceBufferForUser::new(
+            LOCAL_MIC_USER_ID,
+            types::MySpeakingFlags {
+                microphone: true,
+                soundshare: false,
+                priority: false,
+            },
+            audio_callback,
+            voice_activity_config,
+        )));
+        let next_timestamp = Arc::new(Mutex::new(0u32));
+
+        let stream = device
+            .build_input_stream(
+                &supported_config.into(),
+                move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                    let discord_audio = resample_to_discord_stereo(data, sample_rate, channels);
+
+                    let mut pending = pending_for_stream.lock().unwrap();
+                    pending.extend(discord_audio);
+
+                    let frame_len = types::AUDIO_SAMPLES_PER_FRAME * types::AUDIO_CHANNELS;
+                    while pending.len() >= frame_len {
+                        let frame: Vec<types::AudioSample> = pending.drain(..frame_len).collect();
+                        let samples_per_channel = (frame.len() / types::AUDIO_CHANNELS) as u32;
+
+                        let mut timestamp = next_timestamp.lock().unwrap();
+                        voice_buffer.lock().unwrap().push(&frame, *timestamp);
+                        *timestamp = timestamp.wrapping_add(samples_per_channel);
+                    }
+                },
+                |err| eprintln!("microphone capture error: {}", err),
+                None,
+            )
+            .map_err(|err| err.to_string())?;
+
+        stream.play().map_err(|err| err.to_string())?;
+
+        Ok(Self { _stream: stream })
+    }
+}
+
+/// Linearly resample a mono or interleaved `f32` capture buffer to
+/// Discord's 48kHz stereo `i16` PCM. A multi-channel source is first
+/// averaged down to mono; the mono signal is duplicated across both
+/// output channels.
+fn resample_to_discord_stereo(
+    samples: &[f32],
+    sample_rate: u32,
+    channels: usize,
+) -> Vec<types::AudioSample> {
+    let frames_in: Vec<f32> = if channels <= 1 {
+        samples.to_vec()
+    } else {
+        samples
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect()
+    };
+
+    let ratio = types::DISCORD_SAMPLES_PER_SECOND as f64 / sample_rate as f64;
+    let out_frames = (frames_in.len() as f64 * ratio).round() as usize;
+
+    let mut out = Vec::with_capacity(out_frames * types::AUDIO_CHANNELS);
+    for i in 0..out_frames {
+        let src_pos = i as f64 / ratio;
+        let src_index = src_pos.floor() as usize;
+        let frac = src_pos - src_index as f64;
+
+        let a = *frames_in.get(src_index).unwrap_or(&0.0) as f64;
+        let b = *frames_in.get(src_index + 1).unwrap_or(&(a as f32)) as f64;
+        let sample = ((a + (b - a) * frac) * i16::MAX as f64) as types::AudioSample;
+
+        for _ in 0..types::AUDIO_CHANNELS {
+            out.push(sample);
+        }
+    }
+    return out;
+}
@@ -0,0 +1,136 @@
+/// Combines every currently-speaking user's audio into a single 48kHz
+/// stereo stream, so a caller can get one synchronized recording of an
+/// entire voice call instead of per-user clips.
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::types;
+
+/// Samples per channel, per 20ms Discord audio tick.
+const FRAME_SAMPLES_PER_CHANNEL: usize = 960;
+const FRAME_LEN: usize = FRAME_SAMPLES_PER_CHANNEL * types::AUDIO_CHANNELS;
+
+pub type MixedAudioCallback = Arc<dyn Fn(Vec<types::AudioSample>) + Send + Sync>;
+
+/// Combines every active speaker's audio on a fixed 20ms cadence: on each
+/// tick, pulls the next frame from every known SSRC's queue (treating an
+/// absent or exhausted queue as silence), applies that user's gain, and
+/// sums the result with saturating arithmetic so one loud speaker can't
+/// wrap the mix around into noise.
+pub struct Mixer {
+    ssrc_queues: Mutex<HashMap<types::Ssrc, VecDeque<types::AudioSample>>>,
+    ssrc_to_user: Mutex<HashMap<types::Ssrc, types::UserId>>,
+    gain_by_user: Mutex<HashMap<types::UserId, f32>>,
+    on_mixed_frame: MixedAudioCallback,
+}
+
+impl Mixer {
+    pub fn new(on_mixed_frame: MixedAudioCallback) -> Arc<Self> {
+        let mixer = Arc::new(Self {
+            ssrc_queues: Mutex::new(HashMap::new()),
+            ssrc_to_user: Mutex::new(HashMap::new()),
+            gain_by_user: Mutex::new(HashMap::new()),
+            on_mixed_frame,
+        });
+        mixer.clone().spawn_tick_loop();
+        return mixer;
+    }
+
+    /// Set `user_id`'s playback volume, relative to 1.0 (unity gain).
+    pub fn set_user_gain(&self, user_id: types::UserId, gain: f32) {
+        self.gain_by_user.lock().unwrap().insert(user_id, gain);
+    }
+
+    pub fn on_user_join(&self, ssrc: types::Ssrc, user_id: types::UserId) {
+        self.ssrc_to_user.lock().unwrap().insert(ssrc, user_id);
+        self.ssrc_queues
+            .lock()
+            .unwrap()
+            .entry(ssrc)
+            .or_insert_with(VecDeque::new);
+    }
+
+    pub fn on_user_leave(&self, ssrc: types::Ssrc) {
+        self.ssrc_to_user.lock().unwrap().remove(&ssrc);
+        self.ssrc_queues.lock().unwrap().remove(&ssrc);
+    }
+
+    /// Ignores audio for an `ssrc` that hasn't gone through `on_user_join`
+    /// (e.g. a soundshare-only sender while `capture_soundshare` is off) --
+    /// `tick()` only ever drains queues for joined SSRCs, so queuing audio
+    /// for one that never joins would otherwise grow unbounded for the
+    /// life of the call.
+    pub fn on_audio(&self, ssrc: types::Ssrc, audio: &[types::AudioSample]) {
+        let mut ssrc_queues = self.ssrc_queues.lock().unwrap();
+        if let Some(queue) = ssrc_queues.get_mut(&ssrc) {
+            queue.extend(audio);
+        }
+    }
+
+    fn spawn_tick_loop(self: Arc<Self>) {
+        tokio::task::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_millis(
+                types::PERIOD_PER_PACKET_GROUP_MS as u64,
+            ));
+            loop {
+                interval.tick().await;
+                self.tick();
+            }
+        });
+    }
+
+    fn tick(&self) {
+        let ssrc_to_user = self.ssrc_to_user.lock().unwrap().clone();
+        if ssrc_to_user.is_empty() {
+            // nobody's connected yet; nothing to mix.
+            return;
+        }
+        let gain_by_user = self.gain_by_user.lock().unwrap().clone();
+
+        let mut mixed = vec![0i32; FRAME_LEN];
+        {
+            let mut ssrc_queues = self.ssrc_queues.lock().unwrap();
+            for (ssrc, user_id) in &ssrc_to_user {
+                let queue = ssrc_queues.entry(*ssrc).or_insert_with(VecDeque::new);
+                let gain = *gain_by_user.get(user_id).unwrap_or(&1.0);
+
+                for sample_slot in mixed.iter_mut() {
+                    // an empty queue (this user fell silent) contributes
+                    // silence, keeping every speaker's frame in lockstep.
+                    let sample = queue.pop_front().unwrap_or(0) as f32 * gain;
+                    *sample_slot += sample as i32;
+                }
+            }
+        }
+
+        let mixed_samples: Vec<types::AudioSample> = mixed
+            .into_iter()
+            .map(|sample| sample.clamp(i16::MIN as i32, i16::MAX as i32) as types::AudioSample)
+            .collect();
+
+        (self.on_mixed_frame)(mixed_samples);
+    }
+}
+
+/// Build a [`MixedAudioCallback`] that appends each mixed frame to a 48kHz
+/// stereo WAV file at `path`.
+pub fn wav_file_callback(path: String) -> std::io::Result<MixedAudioCallback> {
+    let spec = hound::WavSpec {
+        channels: types::AUDIO_CHANNELS as u16,
+        sample_rate: types::DISCORD_SAMPLES_PER_SECOND as u32,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let writer = hound::WavWriter::create(path, spec)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err.to_string()))?;
+    let writer_mutex = Mutex::new(writer);
+
+    return Ok(Arc::new(move |samples| {
+        let mut writer = writer_mutex.lock().unwrap();
+        for sample in samples {
+            let _ = writer.write_sample(sample);
+        }
+        let _ = writer.flush();
+    }));
+}
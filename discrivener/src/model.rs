@@ -1,6 +1,15 @@
 use async_trait::async_trait;
+use audiopus::coder::Encoder as OpusEncoder;
+use audiopus::{Application, Channels, SampleRate};
+use std::collections::VecDeque;
+use std::io::Cursor;
+use std::sync::mpsc::Receiver;
 use std::sync::Arc;
 
+use songbird::input::codec::OpusDecoderState;
+use songbird::input::{Codec, Container, Input, Reader};
+use songbird::tracks::TrackHandle;
+
 use crate::api_types;
 use crate::packet_handler;
 use crate::types;
@@ -16,6 +25,10 @@ impl Model {
         model_path: String,
         dump_everything_to_a_file: Option<String>,
         event_callback: Arc<dyn Fn(api_types::VoiceChannelEvent) + Send + Sync>,
+        whisper_config: whisper::WhisperConfig,
+        voice_activity_config: crate::voice_buffer::VoiceActivityConfig,
+        capture_config: packet_handler::CaptureConfig,
+        mixer: Option<Arc<crate::mixer::Mixer>>,
     ) -> Self {
         let mut config = songbird::Config::default();
         config.decode_mode = songbird::driver::DecodeMode::Decode; // convert incoming audio from Opus to PCM
@@ -24,13 +37,18 @@ impl Model {
 
         let mut model = Self { driver };
 
-        let whisper = whisper::Whisper::load(model_path, event_callback.clone());
+        let whisper = whisper::Whisper::load(model_path, event_callback.clone(), whisper_config);
 
         let handler_arc = Arc::new(packet_handler::PacketHandler::new(
-            Arc::new(move |user_id, audio| whisper.on_audio_complete(user_id, audio)),
+            Arc::new(move |user_id, audio, clip_start_unixsecs| {
+                whisper.on_audio_complete(user_id, audio, clip_start_unixsecs)
+            }),
             dump_everything_to_a_file,
-            event_callback.clone(),
+            voice_activity_config,
+            capture_config,
+            mixer,
         ));
+        handler_arc.set_self_ref(&handler_arc);
 
         // event handlers for the songbird driver
         model.driver.add_global_event(
@@ -75,6 +93,214 @@ impl Model {
     pub fn disconnect(&mut self) {
         self.driver.leave();
     }
+
+    /// Play a clip of PCM audio into the connected voice channel.
+    ///
+    /// `samples` is interpreted as mono or stereo PCM at `sample_rate`; it is
+    /// resampled to Discord's expected 48kHz stereo, encoded to Opus, and
+    /// handed to the songbird driver as a sequence of 20ms frames. Returns a
+    /// handle the caller can use to pause, stop, or query the playback.
+    pub fn play_audio(&mut self, samples: Vec<i16>, sample_rate: u32, channels: u16) -> TrackHandle {
+        let discord_samples = resample_to_discord_stereo(&samples, sample_rate, channels);
+        let opus_stream = encode_opus_stream(&discord_samples);
+
+        let input = Input::new(
+            true,
+            Reader::from(Box::new(Cursor::new(opus_stream)) as Box<dyn std::io::Read + Send>),
+            Codec::Opus(OpusDecoderState::new().expect("failed to create opus decoder")),
+            // songbird's raw-Opus reader (`Codec::Opus` + `Container::Raw`)
+            // expects each packet prefixed with a `u16` little-endian byte
+            // length, which is exactly what `encode_opus_stream` produces --
+            // `Container::Dca` is the wrong choice here, since DCA also
+            // requires a JSON metadata header before the first frame that we
+            // never write. No automated round-trip test exists in this repo
+            // to confirm the framing end-to-end; if playback ever comes out
+            // as noise, check this pairing first.
+            Container::Raw,
+            None,
+        );
+
+        return self.driver.play_source(input);
+    }
+
+    /// Like `play_audio`, but pulls PCM frames from `frames` as they become
+    /// available instead of requiring the whole clip up front. Each frame is
+    /// resampled and Opus-encoded as it arrives, which suits incrementally
+    /// synthesized audio (e.g. streaming TTS). The stream ends, and playback
+    /// stops, once the sending half of `frames` is dropped.
+    pub fn play_audio_stream(
+        &mut self,
+        frames: Receiver<Vec<i16>>,
+        sample_rate: u32,
+        channels: u16,
+    ) -> TrackHandle {
+        let reader = StreamingOpusReader::new(frames, sample_rate, channels);
+
+        let input = Input::new(
+            true,
+            Reader::from(Box::new(reader) as Box<dyn std::io::Read + Send>),
+            Codec::Opus(OpusDecoderState::new().expect("failed to create opus decoder")),
+            // see the framing note on Container::Raw in play_audio, above.
+            Container::Raw,
+            None,
+        );
+
+        return self.driver.play_source(input);
+    }
+}
+
+/// Number of samples-per-channel in one 20ms Opus frame at Discord's 48kHz.
+const OPUS_FRAME_SAMPLES_PER_CHANNEL: usize = 960;
+
+/// Largest Opus packet `audiopus` will ever hand back for our bitrate/frame
+/// size; per RFC 6716, packets can't exceed this regardless of settings.
+const MAX_OPUS_PACKET_BYTES: usize = 4000;
+
+fn new_opus_encoder() -> OpusEncoder {
+    OpusEncoder::new(SampleRate::Hz48000, Channels::Stereo, Application::Voip)
+        .expect("failed to create opus encoder")
+}
+
+/// Encode interleaved 48kHz stereo PCM into a stream of length-prefixed Opus
+/// frames (a `u16` little-endian byte length, followed by the packet), the
+/// framing `songbird`'s raw Opus reader expects. The final partial frame, if
+/// any, is padded with silence.
+fn encode_opus_stream(discord_samples: &[i16]) -> Vec<u8> {
+    let mut encoder = new_opus_encoder();
+    let frame_len = OPUS_FRAME_SAMPLES_PER_CHANNEL * types::AUDIO_CHANNELS;
+
+    let mut stream = Vec::new();
+    let mut opus_buf = vec![0u8; MAX_OPUS_PACKET_BYTES];
+
+    for frame in discord_samples.chunks(frame_len) {
+        let padded;
+        let frame = if frame.len() == frame_len {
+            frame
+        } else {
+            padded = {
+                let mut padded = frame.to_vec();
+                padded.resize(frame_len, 0);
+                padded
+            };
+            &padded[..]
+        };
+
+        let packet_len = encoder
+            .encode(frame, &mut opus_buf)
+            .expect("opus encode failed");
+        stream.extend_from_slice(&(packet_len as u16).to_le_bytes());
+        stream.extend_from_slice(&opus_buf[..packet_len]);
+    }
+
+    return stream;
+}
+
+/// A [`std::io::Read`] that turns incoming PCM frames from `frames` into the
+/// same length-prefixed Opus byte stream [`encode_opus_stream`] produces,
+/// encoding each frame as soon as it's received rather than all at once.
+struct StreamingOpusReader {
+    frames: Receiver<Vec<i16>>,
+    sample_rate: u32,
+    channels: u16,
+    encoder: OpusEncoder,
+    // discord-rate samples received but not yet long enough to fill a full
+    // Opus frame.
+    carry: Vec<i16>,
+    // encoded bytes (length-prefix + packet) ready to be read out.
+    pending: VecDeque<u8>,
+}
+
+impl StreamingOpusReader {
+    fn new(frames: Receiver<Vec<i16>>, sample_rate: u32, channels: u16) -> Self {
+        Self {
+            frames,
+            sample_rate,
+            channels,
+            encoder: new_opus_encoder(),
+            carry: Vec::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn encode_ready_frames(&mut self) {
+        let frame_len = OPUS_FRAME_SAMPLES_PER_CHANNEL * types::AUDIO_CHANNELS;
+        let mut opus_buf = vec![0u8; MAX_OPUS_PACKET_BYTES];
+
+        while self.carry.len() >= frame_len {
+            let frame: Vec<i16> = self.carry.drain(..frame_len).collect();
+            let packet_len = self
+                .encoder
+                .encode(&frame, &mut opus_buf)
+                .expect("opus encode failed");
+            self.pending
+                .extend((packet_len as u16).to_le_bytes());
+            self.pending.extend(&opus_buf[..packet_len]);
+        }
+    }
+}
+
+impl std::io::Read for StreamingOpusReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pending.is_empty() {
+            match self.frames.recv() {
+                Ok(frame) => {
+                    let discord_samples =
+                        resample_to_discord_stereo(&frame, self.sample_rate, self.channels);
+                    self.carry.extend(discord_samples);
+                    self.encode_ready_frames();
+                }
+                // sender dropped: pad out and encode any partial frame
+                // still carried, as encode_opus_stream does, then end.
+                Err(_) => {
+                    if self.carry.is_empty() {
+                        return Ok(0);
+                    }
+                    self.carry
+                        .resize(OPUS_FRAME_SAMPLES_PER_CHANNEL * types::AUDIO_CHANNELS, 0);
+                    self.encode_ready_frames();
+                }
+            }
+        }
+
+        let n = buf.len().min(self.pending.len());
+        for byte in buf.iter_mut().take(n) {
+            *byte = self.pending.pop_front().unwrap();
+        }
+        return Ok(n);
+    }
+}
+
+/// Resample arbitrary-rate PCM (mono or multi-channel) to Discord's 48kHz
+/// stereo format. A multi-channel source is reduced to its first channel;
+/// the resulting mono signal is duplicated across both output channels.
+fn resample_to_discord_stereo(samples: &[i16], sample_rate: u32, channels: u16) -> Vec<i16> {
+    let frames_in: Vec<i16> = if channels == 1 {
+        samples.to_vec()
+    } else {
+        samples
+            .chunks_exact(channels as usize)
+            .map(|frame| frame[0])
+            .collect()
+    };
+
+    let ratio = types::DISCORD_SAMPLES_PER_SECOND as f64 / sample_rate as f64;
+    let out_frames = (frames_in.len() as f64 * ratio).round() as usize;
+
+    let mut out = Vec::with_capacity(out_frames * types::AUDIO_CHANNELS);
+    for i in 0..out_frames {
+        let src_pos = i as f64 / ratio;
+        let src_index = src_pos.floor() as usize;
+        let frac = src_pos - src_index as f64;
+
+        let a = *frames_in.get(src_index).unwrap_or(&0);
+        let b = *frames_in.get(src_index + 1).unwrap_or(&a);
+        let sample = (a as f64 + (b as f64 - a as f64) * frac).round() as i16;
+
+        for _ in 0..types::AUDIO_CHANNELS {
+            out.push(sample);
+        }
+    }
+    return out;
 }
 
 struct VoicePacketHandlerWrapper {
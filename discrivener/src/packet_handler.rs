@@ -4,12 +4,33 @@ use std::collections::HashMap;
 use std::io::Write;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::Weak;
 
+use crate::mixer;
 use crate::types;
 use crate::voice_buffer;
 
 pub const MAX_NUM_SPEAKING_PARTICIPANTS: usize = 10;
 
+/// Which of Discord's "speaking" flags should be treated as audio worth
+/// buffering and transcribing. Screen-share ("soundshare") audio is off by
+/// default, since it's usually a video call's desktop/game audio rather
+/// than someone talking.
+#[derive(Clone, Copy, Debug)]
+pub struct CaptureConfig {
+    pub capture_microphone: bool,
+    pub capture_soundshare: bool,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            capture_microphone: true,
+            capture_soundshare: false,
+        }
+    }
+}
+
 pub struct PacketHandler {
     // we want to store a VoiceBuffer for each participant who is
     // talking simultaneously. We can use the SSRC to identify each
@@ -23,12 +44,33 @@ pub struct PacketHandler {
     audio_complete_callback: types::AudioCallback,
 
     maybe_log_file_mutex: Option<Arc<Mutex<std::fs::File>>>,
+
+    voice_activity_config: voice_buffer::VoiceActivityConfig,
+
+    capture_config: CaptureConfig,
+
+    // if set, every user's audio is also fed into this mixer, which
+    // combines everyone's audio into a single synchronized stream. Runs
+    // alongside the per-user buffers above; neither affects the other.
+    mixer: Option<Arc<mixer::Mixer>>,
+
+    // used by on_stop_talking's flush timer, below, to tell whether a user
+    // has started talking again before the timer fires.
+    stop_talking_generation: Mutex<HashMap<types::Ssrc, u64>>,
+
+    // weak reference to ourselves, so on_stop_talking's spawned timer can
+    // outlive the `act` call that armed it. Populated once via
+    // `set_self_ref`, immediately after construction.
+    self_weak: Mutex<Option<Weak<PacketHandler>>>,
 }
 
 impl PacketHandler {
     pub fn new(
         audio_complete_callback: types::AudioCallback,
         dump_everything_to_a_file: Option<String>,
+        voice_activity_config: voice_buffer::VoiceActivityConfig,
+        capture_config: CaptureConfig,
+        mixer: Option<Arc<mixer::Mixer>>,
     ) -> Self {
         let mut maybe_log_file_mutex = None;
         if let Some(everything_file) = dump_everything_to_a_file {
@@ -41,51 +83,128 @@ impl PacketHandler {
             ))),
             audio_complete_callback,
             maybe_log_file_mutex,
+            voice_activity_config,
+            capture_config,
+            mixer,
+            stop_talking_generation: Mutex::new(HashMap::new()),
+            self_weak: Mutex::new(None),
         }
     }
 
+    /// Must be called once, immediately after wrapping this handler in an
+    /// `Arc`, so `on_stop_talking` can spawn a flush timer that outlives a
+    /// single `act` call.
+    pub fn set_self_ref(&self, self_arc: &Arc<PacketHandler>) {
+        *self.self_weak.lock().unwrap() = Some(Arc::downgrade(self_arc));
+    }
+
     fn on_user_join(
         &self,
         ssrc: types::Ssrc,
         user_id: types::UserId,
+        flags: types::MySpeakingFlags,
         audio_callback: types::AudioCallback,
     ) {
         let buffer_mutex = self.ssrc_to_user_voice_data.clone();
         let mut ssrc_to_user_voice_data = buffer_mutex.lock().unwrap();
         if let Some(user_voice_data) = ssrc_to_user_voice_data.get_mut(&ssrc) {
             // println!("found existing buffer for ssrc {}", ssrc);
+            // a mid-utterance capability change (e.g. a new SpeakingStateUpdate
+            // toggling soundshare on) reuses the existing buffer rather than
+            // replacing it, so any audio already buffered but not yet flushed
+            // isn't discarded.
             assert!(user_voice_data.user_id == user_id);
+            user_voice_data.flags = flags;
             user_voice_data.on_start_talking();
+        } else {
+            ssrc_to_user_voice_data.insert(
+                ssrc,
+                voice_buffer::VoiceBufferForUser::new(
+                    user_id,
+                    flags,
+                    audio_callback,
+                    self.voice_activity_config,
+                ),
+            );
+        }
+        self.bump_stop_talking_generation(ssrc);
+
+        if let Some(mixer) = &self.mixer {
+            mixer.on_user_join(ssrc, user_id);
         }
-        ssrc_to_user_voice_data.insert(
-            ssrc,
-            voice_buffer::VoiceBufferForUser::new(user_id, audio_callback),
-        );
     }
 
     fn on_start_talking(&self, ssrc: types::Ssrc) {
+        // invalidate any flush timer on_stop_talking armed for this ssrc.
+        self.bump_stop_talking_generation(ssrc);
         self._with_ssrc(ssrc, |user_voice_data| {
             user_voice_data.on_start_talking();
         });
     }
 
-    fn on_audio(&self, ssrc: types::Ssrc, audio: &Vec<types::AudioSample>) {
+    fn on_audio(&self, ssrc: types::Ssrc, audio: &Vec<types::AudioSample>, timestamp: u32) {
         self._with_ssrc(ssrc, |user_voice_data| {
-            user_voice_data.push(audio);
+            user_voice_data.push(audio, timestamp);
         });
+
+        if let Some(mixer) = &self.mixer {
+            mixer.on_audio(ssrc, audio);
+        }
     }
 
+    /// Discord reports that `ssrc` has stopped talking. Rather than flushing
+    /// immediately, arm a timer: if `ssrc` hasn't started talking again by
+    /// the time it fires, flush its buffer as a complete utterance. This
+    /// keeps a brief mid-sentence pause from being split into two clips.
     fn on_stop_talking(&self, ssrc: types::Ssrc) {
-        // set timer to go off in 500ms, and if speaking is still
-        // false then flush the buffer.
-        self._with_ssrc(ssrc, |user_voice_data| {
-            user_voice_data.on_stop_talking();
+        let generation = self.bump_stop_talking_generation(ssrc);
+        let delay_ms = self.voice_activity_config.stop_talking_flush_delay_ms as u64;
+
+        let maybe_self_arc = self.self_weak.lock().unwrap().as_ref().and_then(Weak::upgrade);
+        let Some(self_arc) = maybe_self_arc else {
+            eprintln!("PacketHandler::set_self_ref was never called; can't arm flush timer");
+            return;
+        };
+
+        tokio::task::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+            if self_arc.stop_talking_generation_unchanged(ssrc, generation) {
+                self_arc._with_ssrc(ssrc, |user_voice_data| {
+                    user_voice_data.on_stop_talking();
+                });
+            }
         });
     }
 
+    /// Bump the generation counter for `ssrc`, invalidating any flush timer
+    /// already armed for it, and return the new value.
+    fn bump_stop_talking_generation(&self, ssrc: types::Ssrc) -> u64 {
+        let mut generations = self.stop_talking_generation.lock().unwrap();
+        let generation = generations.entry(ssrc).or_insert(0);
+        *generation += 1;
+        *generation
+    }
+
+    fn stop_talking_generation_unchanged(&self, ssrc: types::Ssrc, generation: u64) -> bool {
+        let generations = self.stop_talking_generation.lock().unwrap();
+        generations.get(&ssrc).copied() == Some(generation)
+    }
+
     fn on_user_leave(&self, user_id: types::UserId) {
         let buffer_mutex = self.ssrc_to_user_voice_data.clone();
         let mut ssrc_to_voice_buffer = buffer_mutex.lock().unwrap();
+
+        let mut generations = self.stop_talking_generation.lock().unwrap();
+        for (ssrc, user_voice_data) in ssrc_to_voice_buffer.iter() {
+            if user_voice_data.user_id == user_id {
+                generations.remove(ssrc);
+                if let Some(mixer) = &self.mixer {
+                    mixer.on_user_leave(*ssrc);
+                }
+            }
+        }
+        drop(generations);
+
         ssrc_to_voice_buffer.retain(|_, user_voice_data| user_voice_data.user_id != user_id);
     }
 
@@ -126,8 +245,8 @@ impl PacketHandler {
     /// if extensions or raw packet data are required.
     ///
     /// Valid audio data (`Some(audio)` where `audio.len >= 0`) contains up to 20ms of 16-bit stereo PCM audio
-    /// at 48kHz, using native endianness. Songbird will not send audio for silent regions, these should
-    /// be inferred using [`SpeakingUpdate`]s (and filled in by the user if required using arrays of zeroes).
+    /// at 48kHz, using native endianness. Songbird will not send audio for silent regions; `VoiceBufferForUser`
+    /// infers these from gaps between packets' RTP timestamps and fills them in with zeroes itself.
     ///
     /// If `audio.len() == 0`, then this packet arrived out-of-order. If `None`, songbird was not configured
     /// to decode received packets.
@@ -166,9 +285,13 @@ impl PacketHandler {
                 //     "Speaking state update: user {:?} has SSRC {:?}, using {:?}",
                 //     user_id, ssrc, speaking,
                 // );
-                // only look at users who are speaking using the microphone
-                // (the alternative is sharing their screen, which we ignore)
-                if speaking.microphone() {
+                // by default we only capture microphone audio; screen-share
+                // ("soundshare") audio is captured too if configured. The
+                // priority flag is just recorded on the buffer, not filtered on.
+                let flags = types::MySpeakingFlags::from(speaking);
+                let should_capture = (flags.microphone && self.capture_config.capture_microphone)
+                    || (flags.soundshare && self.capture_config.capture_soundshare);
+                if should_capture {
                     // make sure we have a buffer for this user
                     if let Some(user_id) = user_id {
                         let callback = self.audio_complete_callback.clone();
@@ -177,9 +300,12 @@ impl PacketHandler {
                         self.on_user_join(
                             *ssrc,
                             user_id.0,
+                            flags,
                             // the user ID passed from voice_buffer is always 0, since
                             // it doesn't know it.  Inject it here.
-                            Arc::new(move |_, audio| (callback)(user_id_copy, audio)),
+                            Arc::new(move |_, audio, clip_start_unixsecs| {
+                                (callback)(user_id_copy, audio, clip_start_unixsecs)
+                            }),
                         );
                     } else {
                         eprintln!("No user_id for speaking state update");
@@ -205,7 +331,7 @@ impl PacketHandler {
             Ctx::VoicePacket(data) => {
                 // An event which fires for every received audio packet,
                 // containing the decoded data.
-                self.on_audio(data.ssrc, &data.audio);
+                self.on_audio(data.ssrc, &data.audio, data.timestamp);
             }
             Ctx::ClientDisconnect(songbird::model::payload::ClientDisconnect {
                 user_id, ..
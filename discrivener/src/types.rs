@@ -30,7 +30,12 @@ pub type AudioClip = Arc<Vec<AudioSample>>;
 pub type UserId = u64;
 pub type Ssrc = u32;
 
-pub type AudioCallback = std::sync::Arc<dyn Fn(UserId, AudioClip) + Sync + Send>;
+/// `u64` is the unix-epoch second at which the clip's first sample was
+/// received locally (i.e. when its buffer started filling, not when it was
+/// flushed) -- there's no Discord-reported wall-clock time to attach to an
+/// RTP stream, so this is the closest available approximation of "when the
+/// clip was spoken."
+pub type AudioCallback = std::sync::Arc<dyn Fn(UserId, AudioClip, u64) + Sync + Send>;
 
 pub const WHISPER_SAMPLES_PER_SECOND: usize = 16000;
 pub type WhisperAudioSample = f32;
@@ -39,6 +44,27 @@ pub type WhisperAudioSample = f32;
 /// We need to do this because we want to serialize them,
 /// for testing and debugging purposes.
 
+/// Which of Discord's "speaking" flags a source has set: it can be
+/// transmitting microphone audio, screen-share ("soundshare") audio, or be
+/// flagged as a priority speaker (e.g. ducking other audio), in any
+/// combination.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct MySpeakingFlags {
+    pub microphone: bool,
+    pub soundshare: bool,
+    pub priority: bool,
+}
+
+impl MySpeakingFlags {
+    pub fn from(speaking: &payload::SpeakingState) -> Self {
+        Self {
+            microphone: speaking.microphone(),
+            soundshare: speaking.soundshare(),
+            priority: speaking.priority(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct MySpeakingUpdateData {
     /// Whether this user is currently speaking.
@@ -66,6 +92,11 @@ pub struct MyVoiceData {
     #[serde_as(as = "Vec<_>")]
     pub audio: Vec<i16>,
     pub ssrc: u32,
+    /// RTP timestamp of this packet, in the 48kHz clock Discord uses for
+    /// voice. Used to detect gaps between packets (dropped or silent
+    /// frames songbird never delivered) and pad them with silence so
+    /// per-user audio stays aligned to wall-clock time.
+    pub timestamp: u32,
 }
 
 impl MyVoiceData {
@@ -73,6 +104,7 @@ impl MyVoiceData {
         Self {
             audio: other.audio.clone().unwrap(),
             ssrc: other.packet.ssrc,
+            timestamp: other.packet.timestamp,
         }
     }
 }
@@ -0,0 +1,278 @@
+use crate::types;
+use ringbuf::{Consumer, Producer};
+use std::sync::Arc;
+use std::sync::Mutex;
+
+type AudioBuffer = ringbuf::HeapRb<types::AudioSample>;
+
+/// Tunables for voice-activity-based segmentation: a clip is flushed once
+/// enough consecutive silence is seen, rather than only when the buffer
+/// fills up.
+#[derive(Clone, Copy, Debug)]
+pub struct VoiceActivityConfig {
+    /// RMS energy (0..i16::MAX) below which a 20ms chunk is considered silent.
+    pub silence_threshold: i32,
+    /// How long a run of silent chunks must last before we flush the
+    /// buffered utterance.
+    pub silence_gap_ms: u32,
+    /// How long to wait after Discord reports a user has stopped talking
+    /// before flushing their buffer, in case they resume within the same
+    /// breath. Reset whenever the user starts talking again.
+    pub stop_talking_flush_delay_ms: u32,
+}
+
+impl Default for VoiceActivityConfig {
+    fn default() -> Self {
+        Self {
+            silence_threshold: 200,
+            silence_gap_ms: 1000,
+            stop_talking_flush_delay_ms: 500,
+        }
+    }
+}
+
+struct VoiceBuffer {
+    // store 30 seconds of audio, 16-bit stereo PCM at 48kHz
+    // divided into 20ms chunks
+
+    // whenever we flush the buffer, its contents are copied out and sent to
+    // decoding, so the buffer can keep filling while that copy is decoded.
+    buffer_mutex: Arc<Mutex<AudioBuffer>>,
+
+    // function to call whenever the buffer is flushed
+    on_buffer_full_fn: types::AudioCallback,
+
+    voice_activity_config: VoiceActivityConfig,
+
+    // how many milliseconds of consecutive silence we've seen since the last
+    // flush; reset whenever we see a chunk above the silence threshold.
+    silent_run_mutex: Mutex<u32>,
+
+    // unix-epoch second at which the first sample of the clip currently
+    // being buffered arrived; `None` while the buffer is empty. Reported
+    // to the callback on flush, since that's the closest thing we have to
+    // "when this clip was spoken."
+    clip_start_unixsecs: Mutex<Option<u64>>,
+}
+
+impl<'a> VoiceBuffer {
+    fn new(callback: types::AudioCallback, voice_activity_config: VoiceActivityConfig) -> Self {
+        let buffer = AudioBuffer::new(types::AUDIO_BUFFER_SIZE);
+
+        Self {
+            buffer_mutex: Arc::new(Mutex::new(buffer)),
+            on_buffer_full_fn: callback,
+            voice_activity_config,
+            silent_run_mutex: Mutex::new(0),
+            clip_start_unixsecs: Mutex::new(None),
+        }
+    }
+
+    /// Append `audio` to the buffer. If there isn't room for it, flush the
+    /// buffer first. Flushing means calling the callback with the buffer's
+    /// contents, which empties the buffer.
+    fn push(&self, audio: &Vec<types::AudioSample>) {
+        // if we have enough space in the buffer, push it there.
+        // if not, flush what's already buffered first, then push.
+        let m = self.buffer_mutex.clone();
+        let mut buffer = m.lock().unwrap();
+        let (mut producer, consumer) = buffer.split_ref();
+
+        if producer.free_len() < audio.len() {
+            self._flush_buffer(&producer, consumer);
+        }
+
+        {
+            let mut clip_start_unixsecs = self.clip_start_unixsecs.lock().unwrap();
+            if clip_start_unixsecs.is_none() {
+                *clip_start_unixsecs = Some(unixsecs_now());
+            }
+        }
+
+        producer.push_slice(audio.as_slice());
+        drop(buffer);
+
+        self.track_silence(audio);
+    }
+
+    /// Update the running silence duration for this packet, and flush
+    /// early if we've now seen a long enough gap of silence to treat the
+    /// buffered audio as a complete utterance.
+    fn track_silence(&self, audio: &Vec<types::AudioSample>) {
+        let rms = rms_energy(audio);
+        let mut silent_run_ms = self.silent_run_mutex.lock().unwrap();
+
+        if rms < self.voice_activity_config.silence_threshold {
+            // count the chunk's real duration, so a backfilled gap of
+            // silence counts for as long as it lasts.
+            let chunk_ms = (audio.len()
+                / types::AUDIO_CHANNELS
+                / types::DISCORD_SAMPLES_PER_MILLISECOND) as u32;
+            *silent_run_ms += chunk_ms;
+        } else {
+            *silent_run_ms = 0;
+        }
+
+        if *silent_run_ms >= self.voice_activity_config.silence_gap_ms {
+            *silent_run_ms = 0;
+            drop(silent_run_ms);
+            self.flush_buffer();
+        }
+    }
+
+    /// Flush the buffer, calling the callback.
+    /// This should consume everything in the buffer.
+    fn flush_buffer(&self) {
+        let mut buffer = self.buffer_mutex.lock().unwrap();
+        let (producer, consumer) = buffer.split_ref();
+        if consumer.is_empty() {
+            return;
+        }
+        self._flush_buffer(&producer, consumer);
+    }
+
+    /// send the buffered audio to decoding. The contents are copied out,
+    /// so that the buffer can continue to fill while the copy is decoded.
+    /// Must be called with the buffer lock held.
+    fn _flush_buffer(
+        &self,
+        producer: &Producer<types::AudioSample, &'a AudioBuffer>,
+        mut consumer: Consumer<types::AudioSample, &'a AudioBuffer>,
+    ) {
+        let buffer_contents = consumer.pop_iter().collect::<Vec<_>>();
+        let audio = Arc::new(buffer_contents);
+
+        let clip_start_unixsecs = self
+            .clip_start_unixsecs
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap_or_else(unixsecs_now);
+
+        // the user ID passed from voice_buffer is always 0, since we don't
+        // know it here; packet_handler::PacketHandler::on_user_join wraps
+        // this callback to inject the real, SSRC-resolved user ID before
+        // it reaches Whisper.
+        (self.on_buffer_full_fn)(0, audio, clip_start_unixsecs);
+
+        // the buffer should be empty now, since we drained it above;
+        // log it if it isn't.
+        if !producer.is_empty() {
+            eprintln!("iter should be empty");
+        }
+    }
+}
+
+/// Current unix-epoch second, used to timestamp a clip's start when it
+/// isn't otherwise known (e.g. the buffer was somehow flushed without ever
+/// recording one).
+fn unixsecs_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Root-mean-square energy of a chunk of (possibly multi-channel) PCM audio.
+fn rms_energy(audio: &[types::AudioSample]) -> i32 {
+    if audio.is_empty() {
+        return 0;
+    }
+    let sum_squares: i64 = audio.iter().map(|sample| (*sample as i64) * (*sample as i64)).sum();
+    ((sum_squares / audio.len() as i64) as f64).sqrt() as i32
+}
+
+/// Largest gap we'll backfill with silence in one go. A gap past this is
+/// more likely a stale/bogus timestamp than a real dropout, so we resync
+/// to it instead of allocating an enormous silent run.
+const MAX_GAP_SAMPLES_PER_CHANNEL: u32 = (types::DISCORD_SAMPLES_PER_SECOND * 10) as u32;
+
+pub struct VoiceBufferForUser {
+    pub user_id: types::UserId,
+    // which of Discord's speaking flags (microphone/soundshare/priority)
+    // this source was sending under, as of the last SpeakingStateUpdate.
+    pub flags: types::MySpeakingFlags,
+    buffer: VoiceBuffer,
+    speaking: bool,
+    // RTP timestamp (48kHz clock) we expect the next packet to start at,
+    // i.e. the previous packet's timestamp plus its duration. `None` until
+    // the first packet arrives, since there's nothing yet to compare against.
+    next_expected_timestamp: Option<u32>,
+}
+
+impl VoiceBufferForUser {
+    pub fn new(
+        user_id: types::UserId,
+        flags: types::MySpeakingFlags,
+        callback: types::AudioCallback,
+        voice_activity_config: VoiceActivityConfig,
+    ) -> Self {
+        Self {
+            user_id,
+            flags,
+            buffer: VoiceBuffer::new(callback, voice_activity_config),
+            speaking: true,
+            next_expected_timestamp: None,
+        }
+    }
+
+    /// `timestamp` is this packet's RTP timestamp, used to detect gaps left
+    /// by packets songbird never delivered (e.g. brief silence it dropped
+    /// rather than sending as zeroed audio) and backfill them with silence,
+    /// so the buffered audio stays aligned to wall-clock time.
+    pub fn push(&mut self, audio: &Vec<types::AudioSample>, timestamp: u32) {
+        if !self.speaking {
+            if audio.iter().all(|sample| *sample == 0) {
+                return;
+            }
+            eprintln!("got audio for non-speaking user {}", self.user_id);
+            return;
+        }
+
+        let samples_per_channel = (audio.len() / types::AUDIO_CHANNELS) as u32;
+        self.fill_gap(timestamp, samples_per_channel);
+
+        self.buffer.push(audio);
+    }
+
+    /// Pad the buffer with silence for any gap between the last packet we
+    /// saw and `timestamp`, then record where the next packet should start.
+    fn fill_gap(&mut self, timestamp: u32, samples_per_channel: u32) {
+        if let Some(expected_timestamp) = self.next_expected_timestamp {
+            // cast to i32 so a timestamp that wrapped around u32::MAX still
+            // produces the correct small delta, and an out-of-order packet
+            // (timestamp before what we expected) comes out negative
+            // instead of as a huge unsigned gap.
+            let delta = timestamp.wrapping_sub(expected_timestamp) as i32;
+            if delta < 0 {
+                // packet arrived out of order; don't try to rewind the
+                // timeline we've already built.
+                return;
+            }
+            if delta > 0 {
+                let gap_samples_per_channel =
+                    (delta as u32).min(MAX_GAP_SAMPLES_PER_CHANNEL);
+                let silence =
+                    vec![0 as types::AudioSample; gap_samples_per_channel as usize * types::AUDIO_CHANNELS];
+                self.buffer.push(&silence);
+            }
+        }
+
+        self.next_expected_timestamp = Some(timestamp.wrapping_add(samples_per_channel));
+    }
+
+    /// Called when a user has started talking after a period of silence.
+    /// This is NOT called when a user starts talking for the first time.
+    pub fn on_start_talking(&mut self) {
+        self.speaking = true;
+        // the gap between this utterance and the last one isn't real audio
+        // dropout; don't let fill_gap backfill it with silence.
+        self.next_expected_timestamp = None;
+    }
+
+    pub fn on_stop_talking(&mut self) {
+        self.speaking = false;
+        self.buffer.flush_buffer();
+        self.next_expected_timestamp = None;
+    }
+}
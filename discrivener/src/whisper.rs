@@ -1,25 +1,101 @@
+use std::collections::{HashMap, VecDeque};
 use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Semaphore};
 use tokio::task;
 use whisper_rs::{FullParams, SamplingStrategy, WhisperContext};
 
 use crate::api_types;
 use crate::types;
 
-/// If an audio clip is less than this length, we'll ignore it.
-pub const MIN_AUDIO_THRESHOLD_MS: u32 = 500;
+/// How whisper.cpp should search for the most likely sequence of tokens.
+#[derive(Clone, Debug)]
+pub enum WhisperSamplingStrategy {
+    /// Always pick the single most likely token. `best_of` controls how many
+    /// candidate decodings are produced for temperature-based resampling;
+    /// has no effect at temperature 0.
+    Greedy { best_of: i32 },
+    /// Keep `beam_size` candidate sequences at each step. Slower than greedy,
+    /// but typically more accurate. `patience` controls how much worse a
+    /// beam can score relative to the best one before it's pruned.
+    BeamSearch { beam_size: i32, patience: f32 },
+}
+
+impl Default for WhisperSamplingStrategy {
+    fn default() -> Self {
+        WhisperSamplingStrategy::Greedy { best_of: 1 }
+    }
+}
+
+/// Tunables for how Whisper decodes a clip of audio into text.
+#[derive(Clone, Debug)]
+pub struct WhisperConfig {
+    /// Spoken language hint, as an ISO 639-1 code (e.g. "en"). `None` lets
+    /// whisper.cpp auto-detect the language from the audio.
+    pub language: Option<String>,
+
+    /// If true, translate the recognized speech into English rather than
+    /// transcribing it in the source language.
+    pub translate: bool,
+
+    /// Greedy or beam-search decoding; see [`WhisperSamplingStrategy`].
+    pub sampling_strategy: WhisperSamplingStrategy,
+
+    /// Optional text used to bias decoding, e.g. expected names or jargon.
+    pub initial_prompt: Option<String>,
+
+    /// Number of CPU threads whisper.cpp may use per decode.
+    pub n_threads: usize,
+
+    /// Temperatures to decode a segment at, in order: the first is used for
+    /// the initial attempt, and each later one for a retry when the previous
+    /// attempt looks unreliable (low average token confidence, or a
+    /// compression ratio suggesting Whisper got stuck repeating itself).
+    /// An empty list decodes once, at temperature 0, with no fallback.
+    pub temperature_fallback: Vec<f32>,
+
+    /// How many clips may be decoding at once. Clips submitted once this
+    /// many are already in flight are dropped rather than queued, so the
+    /// pipeline sheds load instead of building unbounded latency.
+    pub max_concurrent_transcriptions: usize,
+
+    /// Clips whose RMS energy, relative to full scale, falls below this are
+    /// treated as silence/background hiss and dropped before they're ever
+    /// queued for decoding.
+    pub min_energy_to_transcribe: f32,
+}
+
+impl Default for WhisperConfig {
+    fn default() -> Self {
+        Self {
+            language: None,
+            translate: false,
+            sampling_strategy: WhisperSamplingStrategy::default(),
+            initial_prompt: None,
+            n_threads: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4),
+            temperature_fallback: vec![0.0, 0.2, 0.4, 0.6, 0.8, 1.0],
+            max_concurrent_transcriptions: 2,
+            min_energy_to_transcribe: 0.02,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct LastTranscriptionData {
     tokens: Vec<i32>,
     timestamp: u64,
-    user_id: types::UserId,
 }
 
 const MAX_TOKENS_PER_SEGMENT: usize = 100;
 
+/// How long a user's transcription context remains eligible to seed the
+/// next decode for that same user.
+const CONTEXT_WINDOW_SECONDS: u64 = 5;
+
 impl LastTranscriptionData {
     fn from_transcribed_message(
         whisper_context: &WhisperContext,
@@ -37,25 +113,64 @@ impl LastTranscriptionData {
         return LastTranscriptionData {
             tokens,
             timestamp: end_timestmap,
-            user_id: message.user_id,
         };
     }
 }
 
+/// How many recent clips' outcomes (dropped or not) feed the rolling drop
+/// rate used to decide whether to emit [`api_types::VoiceChannelEvent::FallingBehind`].
+const DROP_RATE_WINDOW: usize = 20;
+
+/// Rolling drop rate, as a percentage, at or above which we warn that the
+/// pipeline is falling behind.
+const DROP_RATE_WARNING_PERCENT: u32 = 20;
+
 pub struct Whisper {
+    config: WhisperConfig,
     event_callback: Arc<dyn Fn(api_types::VoiceChannelEvent) + Send + Sync>,
-    last_transcription: Arc<Mutex<Option<LastTranscriptionData>>>,
+    // per-user transcription context, so that two people talking over each
+    // other don't poison each other's Whisper prompt tokens.
+    last_transcription_by_user: Arc<Mutex<HashMap<types::UserId, LastTranscriptionData>>>,
     whisper_context: Arc<WhisperContext>,
+    // bounds how many clips may be decoding at once; acquiring a permit
+    // fails immediately (rather than queuing) once they're all checked out.
+    transcription_permits: Arc<Semaphore>,
+    // whether each of the last DROP_RATE_WINDOW clips was dropped, oldest
+    // first, used to compute the rolling drop rate.
+    recent_drops: Arc<std::sync::Mutex<VecDeque<bool>>>,
+    dropped_clips: Arc<AtomicU32>,
+    total_clips: Arc<AtomicU32>,
 }
 
-fn make_params() -> FullParams<'static, 'static> {
-    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+fn make_params(config: &WhisperConfig, temperature: f32) -> FullParams<'static, 'static> {
+    let strategy = match config.sampling_strategy {
+        WhisperSamplingStrategy::Greedy { best_of } => SamplingStrategy::Greedy { best_of },
+        WhisperSamplingStrategy::BeamSearch {
+            beam_size,
+            patience,
+        } => SamplingStrategy::BeamSearch {
+            beam_size,
+            patience,
+        },
+    };
+    let mut params = FullParams::new(strategy);
 
     params.set_print_special(false);
     params.set_print_progress(false);
     params.set_print_realtime(false);
     params.set_print_timestamps(false);
 
+    params.set_n_threads(config.n_threads as i32);
+    params.set_temperature(temperature);
+
+    params.set_translate(config.translate);
+    if let Some(language) = &config.language {
+        params.set_language(Some(language.as_str()));
+    }
+    if let Some(initial_prompt) = &config.initial_prompt {
+        params.set_initial_prompt(initial_prompt.as_str());
+    }
+
     return params;
 }
 
@@ -64,6 +179,7 @@ impl Whisper {
     pub fn load(
         model_path: String,
         event_callback: Arc<dyn Fn(api_types::VoiceChannelEvent) + Send + Sync>,
+        config: WhisperConfig,
     ) -> Self {
         let path = Path::new(model_path.as_str());
         if !path.exists() {
@@ -76,25 +192,53 @@ impl Whisper {
         let whisper_context =
             Arc::new(WhisperContext::new(model_path.as_str()).expect("failed to load model"));
 
-        let last_transcription = Arc::new(Mutex::new(None));
+        let last_transcription_by_user = Arc::new(Mutex::new(HashMap::new()));
+        let transcription_permits = Arc::new(Semaphore::new(config.max_concurrent_transcriptions));
 
         return Self {
+            config,
             event_callback,
-            last_transcription,
+            last_transcription_by_user,
             whisper_context,
+            transcription_permits,
+            recent_drops: Arc::new(std::sync::Mutex::new(VecDeque::with_capacity(
+                DROP_RATE_WINDOW,
+            ))),
+            dropped_clips: Arc::new(AtomicU32::new(0)),
+            total_clips: Arc::new(AtomicU32::new(0)),
         };
     }
 
-    /// Called once we have a full audio clip from a user.
+    /// Called once we have a full audio clip from a user. `clip_start_unixsecs`
+    /// is when the clip's first sample was received locally, used to fill in
+    /// `TranscribedMessage::timestamp`.
     /// This is called on an event handling thread, so do not do anything
     /// major on it, and return asap.
-    pub fn on_audio_complete(&self, user_id: types::UserId, audio: Arc<Vec<types::AudioSample>>) {
-        let audio_duration_ms =
-            ((audio.len() / types::AUDIO_CHANNELS) / types::DISCORD_SAMPLES_PER_MILLISECOND) as u32;
-        if audio_duration_ms < MIN_AUDIO_THRESHOLD_MS {
-            // very short messages are usually just noise, ignore them
+    pub fn on_audio_complete(
+        &self,
+        user_id: types::UserId,
+        audio: Arc<Vec<types::AudioSample>>,
+        clip_start_unixsecs: u64,
+    ) {
+        if rms_energy(&audio) < self.config.min_energy_to_transcribe {
+            // background hiss / silence; not worth a decode
             return;
         }
+
+        // shed load rather than queue unboundedly: if every permit is
+        // already checked out, drop this clip instead of piling up latency.
+        let permit = match self.transcription_permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                self.record_clip_outcome(true);
+                return;
+            }
+        };
+        self.record_clip_outcome(false);
+
+        let audio_duration_ms =
+            ((audio.len() / types::AUDIO_CHANNELS) / types::DISCORD_SAMPLES_PER_MILLISECOND) as u32;
+
         // get our unixtime in ms
         let start_time = std::time::SystemTime::now();
         let unixsecs = start_time
@@ -105,35 +249,34 @@ impl Whisper {
         // make clones of everything so that the closure can own them, if
         let audio_copy = audio.clone();
         let callback_copy = self.event_callback.clone();
-        let last_transcription_copy = self.last_transcription.clone();
+        let last_transcription_copy = self.last_transcription_by_user.clone();
         let whisper_context_copy = self.whisper_context.clone();
+        let config_copy = self.config.clone();
 
-        // todo: if we're running too far behind, we should drop audio in order to catch up
-        // todo: if we're always running too far behind, we should display some kind of warning
         // todo: try quantized model?
 
         task::spawn(async move {
+            // holds our backpressure permit for the lifetime of the task
+            let _permit = permit;
+
             let whisper_audio = resample_audio_from_discord_to_whisper(audio_copy);
 
-            // get the last transcription, and pass it in if:
-            // - it's from the same user
-            // - the last transcription ended less than 5 seconds ago
-            let mut last_transcription_context: Option<LastTranscriptionData> = None;
-            {
-                let last_transcription = last_transcription_copy.lock().await;
-                let lt = last_transcription.clone();
-                if let Some(last_transcription) = lt {
-                    if (unixsecs - last_transcription.timestamp) < 5 {
-                        if last_transcription.user_id == user_id {
-                            last_transcription_context = Some(last_transcription);
-                        }
-                    }
-                }
-            }
+            // get this user's last transcription, and pass it in as decoding
+            // context if it ended less than CONTEXT_WINDOW_SECONDS ago.
+            // while we're here, prune any other users' contexts that have
+            // aged out, so the map can't grow unbounded across a long
+            // session.
+            let last_transcription_context = {
+                let mut last_transcription_by_user = last_transcription_copy.lock().await;
+                last_transcription_by_user
+                    .retain(|_, data| unixsecs.saturating_sub(data.timestamp) < CONTEXT_WINDOW_SECONDS);
+                last_transcription_by_user.get(&user_id).cloned()
+            };
             let text_segments = audio_to_text(
                 &whisper_context_copy,
                 whisper_audio,
                 last_transcription_context,
+                &config_copy,
             );
 
             // if there's nothing in the last transcription, then just stop
@@ -145,7 +288,7 @@ impl Whisper {
             let processing_time_ms =
                 end_time.duration_since(start_time).unwrap().as_millis() as u32;
             let transcribed_message = api_types::TranscribedMessage {
-                timestamp: unixsecs,
+                timestamp: clip_start_unixsecs,
                 user_id,
                 text_segments,
                 audio_duration_ms,
@@ -162,7 +305,10 @@ impl Whisper {
                     .as_secs(),
             );
             {
-                last_transcription_copy.lock().await.replace(last_data);
+                last_transcription_copy
+                    .lock()
+                    .await
+                    .insert(user_id, last_data);
             }
 
             callback_copy(api_types::VoiceChannelEvent::TranscribedMessage(
@@ -170,89 +316,273 @@ impl Whisper {
             ));
         });
     }
+
+    /// Record whether a clip was just dropped or accepted, and if the
+    /// rolling drop rate over the last `DROP_RATE_WINDOW` clips crosses
+    /// `DROP_RATE_WARNING_PERCENT`, let the caller know we're falling
+    /// behind.
+    fn record_clip_outcome(&self, dropped: bool) {
+        self.total_clips.fetch_add(1, Ordering::Relaxed);
+        if dropped {
+            self.dropped_clips.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let (dropped_in_window, total_in_window) = {
+            let mut recent_drops = self.recent_drops.lock().unwrap();
+            if recent_drops.len() == DROP_RATE_WINDOW {
+                recent_drops.pop_front();
+            }
+            recent_drops.push_back(dropped);
+            let dropped_in_window = recent_drops.iter().filter(|d| **d).count() as u32;
+            (dropped_in_window, recent_drops.len() as u32)
+        };
+
+        let drop_rate_percent = dropped_in_window * 100 / total_in_window;
+        if drop_rate_percent >= DROP_RATE_WARNING_PERCENT {
+            (self.event_callback)(api_types::VoiceChannelEvent::FallingBehind(
+                api_types::FallingBehindData {
+                    dropped_clips: dropped_in_window,
+                    total_clips: total_in_window,
+                    drop_rate_percent,
+                },
+            ));
+        }
+    }
+}
+
+/// RMS energy of `audio`, relative to full scale (i.e. in `[0.0, 1.0]`).
+fn rms_energy(audio: &[types::AudioSample]) -> f32 {
+    if audio.is_empty() {
+        return 0.0;
+    }
+    let sum_of_squares: f64 = audio.iter().map(|sample| (*sample as f64).powi(2)).sum();
+    let rms = (sum_of_squares / audio.len() as f64).sqrt();
+    return (rms / i16::MAX as f64) as f32;
 }
 
 fn resample_audio_from_discord_to_whisper(
     audio: types::AudioClip,
 ) -> Vec<types::WhisperAudioSample> {
-    // this takes advantage of the ratio between the two sample rates
-    // being a whole number. If this is not the case, we'll need to
-    // do some more complicated resampling.
-    assert!(types::DISCORD_SAMPLES_PER_SECOND % types::WHISPER_SAMPLES_PER_SECOND == 0);
-    const BITRATE_CONVERSION_RATIO: usize =
-        types::DISCORD_SAMPLES_PER_SECOND / types::WHISPER_SAMPLES_PER_SECOND;
-
-    // do the conversion, we'll take the first sample, and then
-    // simply skip over the next (BITRATE_CONVERSION_RATIO-1)
-    // samples
-    //
-    // while converting the bitrate we'll also convert the audio
-    // from stereo to mono, so we'll do everything in pairs.
-    const GROUP_SIZE: usize = BITRATE_CONVERSION_RATIO * types::AUDIO_CHANNELS;
-
-    let out_len = audio.len() / GROUP_SIZE;
-    let mut audio_out = vec![0.0 as types::WhisperAudioSample; out_len];
-
-    let mut audio_max: types::WhisperAudioSample = 0.0;
-
-    // todo: drop audio which is very low signal?  It has had issues transcribing well.
-
-    // iterate through the audio vector, taking pairs of samples and averaging them
-    // while doing so, look for max and min values so that we can normalize later
-    for (i, samples) in audio.chunks_exact(GROUP_SIZE).enumerate() {
-        // take the first two values of samples, and add them into audio_out .
-        // also, find the largest absolute value, and store it in audio_max
-        let mut val = 0.0;
-        for j in 0..types::AUDIO_CHANNELS {
-            val += samples[j] as types::WhisperAudioSample;
-        }
-        let abs = val.abs();
+    resample_to_whisper(&audio, types::DISCORD_SAMPLES_PER_SECOND)
+}
+
+/// Downmix stereo PCM at `source_rate` Hz to mono, and resample it to the
+/// rate Whisper expects, anti-aliasing with a windowed-sinc polyphase
+/// filter. Unlike naive decimation, this works for any `source_rate` (not
+/// just whole multiples of [`types::WHISPER_SAMPLES_PER_SECOND`]), so other
+/// capture sources (e.g. a local microphone) can feed it directly.
+fn resample_to_whisper(
+    audio: &[types::AudioSample],
+    source_rate: usize,
+) -> Vec<types::WhisperAudioSample> {
+    let mono: Vec<f64> = audio
+        .chunks_exact(types::AUDIO_CHANNELS)
+        .map(|frame| {
+            let mut val = 0.0;
+            for sample in frame {
+                val += *sample as f64;
+            }
+            val / types::AUDIO_CHANNELS as f64
+        })
+        .collect();
+
+    let resampled = polyphase_resample(&mono, source_rate, types::WHISPER_SAMPLES_PER_SECOND);
+
+    let mut audio_max: f64 = 0.0;
+    for sample in &resampled {
+        let abs = sample.abs();
         if abs > audio_max {
             audio_max = abs;
         }
-        audio_out[i] = val;
-        // don't worry about dividing by AUDIO_CHANNELS, as normalizing
-        // will take care of it, saving us divisions
     }
-    // normalize floats to be between -1 and 1
-    for sample in audio_out.iter_mut() {
-        *sample /= audio_max;
+    if audio_max == 0.0 {
+        audio_max = 1.0;
+    }
+
+    // very-low-signal clips don't reach here: on_audio_complete drops any
+    // below `min_energy_to_transcribe` before queuing them.
+
+    return resampled
+        .into_iter()
+        .map(|sample| (sample / audio_max) as types::WhisperAudioSample)
+        .collect();
+}
+
+/// Number of FIR taps per polyphase branch; higher means a sharper, more
+/// accurate anti-aliasing filter at the cost of more compute per sample.
+const TAPS_PER_PHASE: usize = 16;
+
+/// Resample `input` from `source_rate` to `target_rate` using a polyphase
+/// FIR filter: `input` is conceptually upsampled by `l`, band-limited to
+/// the lower of the two Nyquist rates, then decimated by `m`, where `l/m`
+/// is `target_rate/source_rate` reduced to lowest terms. Doing this as a
+/// single polyphase filter (rather than separate upsample/filter/decimate
+/// passes) avoids ever materializing the zero-stuffed upsampled signal.
+fn polyphase_resample(input: &[f64], source_rate: usize, target_rate: usize) -> Vec<f64> {
+    let divisor = gcd(source_rate, target_rate).max(1);
+    let l = target_rate / divisor;
+    let m = source_rate / divisor;
+
+    // Cutoff is the more restrictive of the two Nyquist rates, normalized
+    // to the virtual upsampled-by-`l` sample rate.
+    let cutoff = 1.0 / l.max(m) as f64;
+    let prototype = windowed_sinc_lowpass(cutoff, TAPS_PER_PHASE * l);
+    // Phase `p` of the polyphase filter is every `l`th tap of the
+    // prototype, starting at offset `p`.
+    let phases: Vec<Vec<f64>> = (0..l)
+        .map(|p| prototype.iter().skip(p).step_by(l).cloned().collect())
+        .collect();
+
+    let out_len = ((input.len() as u64) * (l as u64) / (m as u64)) as usize;
+    let mut output = Vec::with_capacity(out_len);
+
+    for n in 0..out_len {
+        // Index into the virtual, upsampled-by-`l` signal.
+        let up_index = n * m;
+        let phase = &phases[up_index % l];
+        let center = (up_index / l) as isize;
+        let half = (phase.len() / 2) as isize;
+
+        let mut acc = 0.0;
+        for (k, tap) in phase.iter().enumerate() {
+            let idx = center + k as isize - half;
+            if idx >= 0 && (idx as usize) < input.len() {
+                acc += tap * input[idx as usize];
+            }
+        }
+        // Compensate for the zero-stuffing gain loss of the implicit
+        // upsample-by-`l` step.
+        output.push(acc * l as f64);
+    }
+    return output;
+}
+
+/// A lowpass FIR filter of `num_taps` taps with cutoff frequency `cutoff`
+/// (normalized so that 1.0 == the Nyquist rate), windowed with a Hamming
+/// window to control ripple/stopband attenuation.
+fn windowed_sinc_lowpass(cutoff: f64, num_taps: usize) -> Vec<f64> {
+    let num_taps = num_taps.max(1);
+    let center = (num_taps - 1) as f64 / 2.0;
+    (0..num_taps)
+        .map(|i| {
+            let x = i as f64 - center;
+            let sinc = if x == 0.0 {
+                cutoff
+            } else {
+                (std::f64::consts::PI * cutoff * x).sin() / (std::f64::consts::PI * x)
+            };
+            let window =
+                0.54 - 0.46 * (2.0 * std::f64::consts::PI * i as f64 / (num_taps - 1).max(1) as f64).cos();
+            sinc * window
+        })
+        .collect()
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
     }
-    return audio_out;
 }
 
+/// Average per-token log probability below this is treated as a
+/// low-confidence decode, eligible for a temperature-fallback retry.
+const LOGPROB_FALLBACK_THRESHOLD: f64 = -1.0;
+
+/// Text-length-to-compressed-length ratio above this usually means Whisper
+/// fell into a repetition loop, and is also eligible for a retry.
+const COMPRESSION_RATIO_FALLBACK_THRESHOLD: f64 = 2.4;
+
 /// ctx came from load_model
 /// audio data should be is f32, 16KHz, mono
 fn audio_to_text(
     whisper_context: &Arc<WhisperContext>,
     audio_data: Vec<types::WhisperAudioSample>,
     last_transcription: Option<LastTranscriptionData>,
+    config: &WhisperConfig,
 ) -> Vec<api_types::TextSegment> {
+    let last_tokens = last_transcription.map(|data| data.tokens);
+
+    let default_temperatures = [0.0];
+    let temperatures: &[f32] = if config.temperature_fallback.is_empty() {
+        &default_temperatures
+    } else {
+        &config.temperature_fallback
+    };
+
     let mut state = whisper_context.create_state().unwrap();
+    let mut result = Vec::<api_types::TextSegment>::new();
+
+    // whisper.cpp-style temperature fallback: decode at the first
+    // temperature, and if the result looks unreliable, retry the same clip
+    // at the next temperature, giving the sampler more randomness to escape
+    // a degenerate decode.
+    for (attempt, temperature) in temperatures.iter().enumerate() {
+        let is_last_attempt = attempt + 1 == temperatures.len();
+
+        let mut params = make_params(config, *temperature);
+        if let Some(tokens) = &last_tokens {
+            params.set_tokens(&tokens[..]);
+        }
 
-    let mut params = make_params();
+        // actually convert audio to text.  Takes a while.
+        state.full(params, &audio_data[..]).unwrap();
 
-    // if we have a last_transcription, add it to the state
-    let last_tokens;
-    if last_transcription.is_some() {
-        last_tokens = last_transcription.unwrap().tokens;
-        params.set_tokens(&last_tokens[..]);
-    }
+        let num_segments = state.full_n_segments().unwrap();
+        let text: String = (0..num_segments)
+            .filter_map(|i| state.full_get_segment_text(i).ok())
+            .collect::<Vec<_>>()
+            .concat();
 
-    // actually convert audio to text.  Takes a while.
-    state.full(params, &audio_data[..]).unwrap();
+        let confident = is_last_attempt || {
+            let mut logprob_total = 0.0;
+            let mut logprob_count = 0u32;
+            for i in 0..num_segments {
+                for j in 0..state.full_n_tokens(i).unwrap_or(0) {
+                    if let Ok(token) = state.full_get_token_data(i, j) {
+                        logprob_total += token.plog as f64;
+                        logprob_count += 1;
+                    }
+                }
+            }
+            let average_logprob = if logprob_count == 0 {
+                0.0
+            } else {
+                logprob_total / logprob_count as f64
+            };
 
-    // todo: use a different context / token history for each user
-    // see https://github.com/ggerganov/whisper.cpp/blob/57543c169e27312e7546d07ed0d8c6eb806ebc36/examples/stream/stream.cpp
+            average_logprob >= LOGPROB_FALLBACK_THRESHOLD
+                && compression_ratio(&text) <= COMPRESSION_RATIO_FALLBACK_THRESHOLD
+        };
 
-    let num_segments = state.full_n_segments().unwrap();
-    let mut result = Vec::<api_types::TextSegment>::with_capacity(num_segments as usize);
-    for i in 0..num_segments {
-        result.push(api_types::TextSegment {
-            text: state.full_get_segment_text(i).unwrap().to_string(),
-            start_offset_ms: state.full_get_segment_t0(i).unwrap() as u32,
-            end_offset_ms: state.full_get_segment_t1(i).unwrap() as u32,
-        });
+        if confident {
+            result = (0..num_segments)
+                .map(|i| api_types::TextSegment {
+                    text: state.full_get_segment_text(i).unwrap().to_string(),
+                    start_offset_ms: state.full_get_segment_t0(i).unwrap() as u32,
+                    end_offset_ms: state.full_get_segment_t1(i).unwrap() as u32,
+                })
+                .collect();
+            break;
+        }
     }
     return result;
 }
+
+/// Ratio of `text`'s length to its zlib-compressed length: a cheap proxy
+/// for "Whisper got stuck repeating itself", since repetitive text
+/// compresses far better than normal speech.
+fn compression_ratio(text: &str) -> f64 {
+    use std::io::Write;
+
+    if text.is_empty() {
+        return 0.0;
+    }
+
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(text.as_bytes()).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    return text.len() as f64 / compressed.len().max(1) as f64;
+}